@@ -10,19 +10,21 @@
 // This code is hereby in the public domain.  Caveat you!
 //
 
-#[macro_use]
-extern crate lazy_static;
-
 use std::path::{Path, PathBuf};
 use std::io::{BufRead, BufReader};
 use std::fs::File;
 use std::fs;
 use std::ffi::OsString;
+use std::process::Command;
+use std::sync::{Condvar, Mutex};
+use std::collections::HashSet;
 
 use clap::{App, Arg};
 use snafu::{ResultExt, Snafu};
 use exif::Exif;
-use regex::Regex;
+use serde::Deserialize;
+use rayon::prelude::*;
+use indicatif::{ParallelProgressIterator, ProgressBar};
 
 #[derive(Debug, Snafu)]
 enum Error {
@@ -32,12 +34,6 @@ enum Error {
         path: PathBuf,
         source: std::io::Error,
     },
-    #[snafu(display("Could not parse input file '{}': {}",
-                    path.display(), source))]
-    Parse {
-        path: PathBuf,
-        source: exif::Error,
-    },
     #[snafu(display("missing field in input file '{}': {}",
                     path.display(), field))]
     MissingField {
@@ -50,6 +46,111 @@ enum Error {
         path: PathBuf,
         len: u64,
     },
+    #[snafu(display("could not run exiftool on '{}': {} (is exiftool installed?)",
+                    path.display(), source))]
+    ExiftoolSpawn {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("exiftool exited with status {} for '{}'",
+                    status, path.display()))]
+    ExiftoolFailed {
+        path: PathBuf,
+        status: i32,
+    },
+    #[snafu(display("could not parse exiftool output for '{}': {}",
+                    path.display(), source))]
+    ExiftoolParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display("exiftool reported no CreateDate for '{}'",
+                    path.display()))]
+    ExiftoolNoDate {
+        path: PathBuf,
+    },
+    #[snafu(display("could not read filesystem metadata for '{}': {}",
+                    path.display(), source))]
+    Metadata {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("could not create directory '{}': {}",
+                    path.display(), source))]
+    Mkdir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("could not link '{}': {}",
+                    path.display(), source))]
+    Symlink {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("could not copy to '{}': {}",
+                    path.display(), source))]
+    CopyFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("could not move to '{}': {}",
+                    path.display(), source))]
+    MoveFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("could not hash '{}': {}",
+                    path.display(), source))]
+    Hash {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("'{}' already exists with different content and no free disambiguated name was found",
+                    path.display()))]
+    Conflict {
+        path: PathBuf,
+    },
+    #[snafu(display("could not parse date '{}' from '{}': {}",
+                    raw, path.display(), source))]
+    TemplateDate {
+        path: PathBuf,
+        raw: String,
+        source: chrono::ParseError,
+    },
+}
+
+//
+// A single entry from `exiftool -json -CreateDate <path>`, which always
+// prints a one-element JSON array.
+//
+#[derive(Debug, Deserialize)]
+struct ExiftoolEntry {
+    #[serde(rename = "CreateDate")]
+    create_date: Option<String>,
+}
+
+//
+// Where the date we linked a file by came from, so `--verbose` can report
+// which path was taken for a given file.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateSource {
+    Exif,
+    Exiftool,
+    Mtime,
+}
+
+//
+// The result of `get_exif`: either a fully parsed native EXIF container, a
+// date string pulled out of `exiftool`'s output (when the exif crate can't
+// parse the format, e.g. MOV/HEIC/RAW), or a filesystem mtime used when
+// `get_exif` itself failed (too small, exiftool missing/failed/no date)
+// and `--no-mtime-fallback` wasn't given.
+//
+enum ExifData {
+    Native(Exif),
+    Exiftool(String),
+    Mtime(String),
 }
 
 //
@@ -60,10 +161,63 @@ const DATETIME: &[exif::Tag] = &[exif::Tag::DateTimeDigitized,
                exif::Tag::DateTime,
         ];
 
-lazy_static! {
-    static ref DATE_REGEX : Regex = Regex::new(
-        r"(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2}) (?P<H>\d{2}):(?P<M>\d{2}):(?P<S>\d{2})"
-    ).unwrap();
+//
+// How dates come out of EXIF/exiftool/mtime: "%Y-%m-%d %H:%M:%S".
+//
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+//
+// Extensions we always hand to the exiftool fallback: video containers
+// and RAW formats where file size isn't a useful signal for "is this a
+// real capture or an embedded thumbnail", so `--min-size` is skipped.
+//
+const EXIFTOOL_EXTENSIONS: &[&str] = &[
+    "mov", "mp4", "m4v", "avi", "mkv",
+    "heic", "heif",
+    "raw", "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2",
+];
+
+fn is_exiftool_ext (path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .map(|e| EXIFTOOL_EXTENSIONS.contains(&e.as_str()))
+        .unwrap_or(false)
+}
+
+//
+// Parse a `--min-size` value, accepting a plain byte count or a human
+// shorthand with a K/M/G suffix (binary units, e.g. "100K" == 102400).
+//
+fn parse_size (s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, mult) = match s.chars().last() {
+        Some(c) if c.eq_ignore_ascii_case(&'k') => (&s[..s.len() - 1], 1024),
+        Some(c) if c.eq_ignore_ascii_case(&'m') => (&s[..s.len() - 1], 1024 * 1024),
+        Some(c) if c.eq_ignore_ascii_case(&'g') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+
+    digits.trim().parse::<u64>()
+        .map(|n| n * mult)
+        .map_err(|_| format!("invalid --min-size value '{}'", s))
+}
+
+//
+// The default `--template`, reproducing the hierarchy this tool has
+// always produced: y/m/d/H-M-S-, with the original filename appended
+// directly after the trailing dash.
+//
+const DEFAULT_TEMPLATE: &str = "%Y/%m/%d/%H-%M-%S-";
+
+//
+// Print a line "above" the progress bar.  `ProgressBar::println` is a
+// no-op whenever the bar's draw target is hidden (e.g. stdout isn't a
+// tty, as with `--no-execute` previews or output piped to a file/cron),
+// which would silently swallow every action and --verbose line, so we
+// suspend the bar instead and fall back to a plain `println!`.
+//
+fn say (pb: &ProgressBar, msg: impl std::fmt::Display) {
+    pb.suspend(|| println!("{}", msg));
 }
 
 fn main() {
@@ -109,10 +263,58 @@ fn main() {
                 .long("verbose")
                 .help("Make some noise!"),
         )
+        .arg(
+            Arg::with_name("no-mtime-fallback")
+                .long("no-mtime-fallback")
+                .help("Don't fall back to filesystem timestamps when no EXIF/exiftool date is found"),
+        )
+        .arg(
+            Arg::with_name("copy")
+                .short("c")
+                .long("copy")
+                .conflicts_with("move")
+                .help("Copy files into the date tree instead of symlinking them"),
+        )
+        .arg(
+            Arg::with_name("move")
+                .short("m")
+                .long("move")
+                .conflicts_with("copy")
+                .help("Move files into the date tree instead of symlinking them"),
+        )
+        .arg(
+            Arg::with_name("template")
+                .short("t")
+                .long("template")
+                .takes_value(true)
+                .help("chrono strftime template for the output directory hierarchy"),
+        )
+        .arg(
+            Arg::with_name("min-size")
+                .long("min-size")
+                .takes_value(true)
+                .help("Minimum file size to consider a real capture, not a thumbnail (e.g. 100K, 2M); 0 disables"),
+        )
         .get_matches();
 
     let verbose = args.is_present("verbose");
     let dump = args.is_present("exif");
+    let mtime_fallback = !args.is_present("no-mtime-fallback");
+    let mode = if args.is_present("copy") {
+        LinkMode::Copy
+    } else if args.is_present("move") {
+        LinkMode::Move
+    } else {
+        LinkMode::Symlink
+    };
+    let template = args.value_of("template").unwrap_or(DEFAULT_TEMPLATE);
+    let min_size = match args.value_of("min-size") {
+        Some(s) => parse_size(s).unwrap_or_else(|e| {
+            eprintln!("# error: {}", e);
+            std::process::exit(1);
+        }),
+        None => 100 * 1024,
+    };
 
     let base_set;
     let base;
@@ -166,106 +368,419 @@ fn main() {
         println!("# executing...");
     }
 
-    'file: for file in files {
-        if verbose {
-            println!("# working: {:?}", file);
+    let opts = Options { base, base_set, dump, no_execute, verbose,
+                         mtime_fallback, mode, template, min_size };
+
+    let mkdir_lock = Mutex::new(());
+    // See `TargetLock`: lets threads placing files at different targets
+    // run in parallel while colliding ones serialize against each other.
+    let target_lock = TargetLock::new();
+    // Worker threads print their "mkdir -p"/"ln -s"/"cp"/"mv" lines (and any
+    // --verbose tracing) through `say`, which suspends the bar to print
+    // above it instead of racing it on stdout.
+    let pb = ProgressBar::new(files.len() as u64);
+
+    let results: Vec<Result<(), Error>> = files
+        .par_iter()
+        .progress_with(pb.clone())
+        .map(|file| process_file(file, &opts, &mkdir_lock, &target_lock, &pb))
+        .collect();
+
+    pb.finish_and_clear();
+
+    let errors: Vec<&Error> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+    if !errors.is_empty() {
+        println!("# {} of {} file(s) failed:", errors.len(), files.len());
+        for e in &errors {
+            println!("#   {}", e);
         }
+    }
+}
 
-        let mut source = PathBuf::new();
-        if base_set {
-            source.push("..");
-        }
+//
+// Everything about how to run a single file through the pipeline, bundled
+// into one struct instead of another positional parameter on
+// `process_file` every time a new flag (--copy/--move, --template,
+// --min-size, ...) comes along.
+//
+struct Options<'a> {
+    base: &'a str,
+    base_set: bool,
+    dump: bool,
+    no_execute: bool,
+    verbose: bool,
+    mtime_fallback: bool,
+    mode: LinkMode,
+    template: &'a str,
+    min_size: u64,
+}
 
-        source.push("../../..");
-        source.push(&file);
-        let src = source.as_path();
+//
+// Do all the work for a single file: find its date, work out its target
+// path, and (unless we're dumping or running with --no-execute) create
+// the target directory and symlink.  This runs on a rayon worker thread,
+// so directory creation is serialized with `mkdir_lock` (since
+// `fs::create_dir_all` races when two threads create overlapping parent
+// directories at once) and placement at a colliding target is serialized
+// via `target_lock` (see `place_file`).
+//
+fn process_file (file: &Path, opts: &Options, mkdir_lock: &Mutex<()>,
+                 target_lock: &TargetLock, pb: &ProgressBar) -> Result<(), Error> {
+    if opts.verbose {
+        say(pb, format!("# working: {:?}", file));
+    }
 
-        let exif = {
-            match get_exif(&file, dump) {
-                Ok(e) => e,
-                Err(e) => { println!("# error: {}", e); continue 'file; },
-            }
-        };
+    let mut source = PathBuf::new();
+    if opts.base_set {
+        source.push("..");
+    }
 
-        let targ = {
-            match link_name(&exif, &file, base, verbose) {
-                Ok(targ) => targ,
-                Err(e) => { println!("# error: {}", e); continue 'file; },
+    source.push("../../..");
+    source.push(file);
+    let src = source.as_path();
+
+    let exif = match get_exif(file, opts.dump, opts.min_size, pb) {
+        Ok(exif) => exif,
+        // `--min-size` is a deliberate exclusion, not a missing date: always
+        // skip the file, even with the mtime fallback enabled.
+        Err(e @ Error::TooSmall { .. }) => return Err(e),
+        Err(e) => {
+            if !opts.mtime_fallback {
+                return Err(e);
+            }
+            if opts.verbose {
+                say(pb, format!("# {}, falling back to mtime", e));
             }
-        };
+            ExifData::Mtime(mtime_date(file)?)
+        },
+    };
+    let targ = link_name(&exif, file, opts, pb)?;
 
-        if dump {
-            println!("# target: {:?}", targ);
-            continue;
-        }
+    if opts.dump {
+        say(pb, format!("# target: {:?}", targ));
+        return Ok(());
+    }
 
-        if verbose {
-            println!("# linking {:?} to {:?} ... ", src, targ);
-        }
+    if opts.verbose {
+        say(pb, format!("# placing ({:?}) {:?} at {:?} ... ", opts.mode, src, targ));
+    }
 
-        if let Some(parent) = targ.parent() {
+    if let Some(parent) = targ.parent() {
+        if !parent.exists() {
+            let _guard = mkdir_lock.lock().unwrap();
             if !parent.exists() {
-                println!("mkdir -p {:?}", parent);
-                if !no_execute {
-                    if let Err(e) = fs::create_dir_all(parent) {
-                        println!("# error: {:?}", e);
-                    }
+                say(pb, format!("mkdir -p {:?}", parent));
+                if !opts.no_execute {
+                    fs::create_dir_all(parent)
+                        .context(Mkdir { path: parent.to_path_buf() })?;
                 }
             }
         }
+    }
+
+    place_file(file, src, &targ, opts, target_lock, pb)
+}
+
+//
+// How an input file is placed into the date tree.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LinkMode {
+    Symlink,
+    Copy,
+    Move,
+}
+
+//
+// Tracks which computed target paths are currently being placed, so two
+// worker threads whose files collide on the same target (e.g. two
+// cameras each producing IMG_0001.*) serialize against each other while
+// threads placing files at unrelated targets keep running in parallel.
+//
+struct TargetLock {
+    inflight: Mutex<HashSet<PathBuf>>,
+    cvar: Condvar,
+}
+
+impl TargetLock {
+    fn new () -> Self {
+        TargetLock { inflight: Mutex::new(HashSet::new()), cvar: Condvar::new() }
+    }
+
+    // Block until no other thread holds `path`, then claim it, returning a
+    // guard that releases the claim on drop, whether `place_file` returns
+    // normally or unwinds.
+    fn claim<'a> (&'a self, path: &Path) -> TargetClaim<'a> {
+        let mut claim = TargetClaim { lock: self, path: None };
+        claim.switch(path);
+        claim
+    }
+}
+
+//
+// Holds exactly one claimed path at a time.  `switch` releases whatever
+// was previously held before blocking on the new path, rather than
+// accumulating claims, so a thread walking the disambiguation chain
+// never holds more than one path at once -- which is what makes the
+// claim-another-path-while-waiting-for-one deadlock impossible, and
+// avoids needlessly blocking other threads on candidates this one has
+// already ruled out.
+//
+struct TargetClaim<'a> {
+    lock: &'a TargetLock,
+    path: Option<PathBuf>,
+}
+
+impl<'a> TargetClaim<'a> {
+    fn switch (&mut self, path: &Path) {
+        self.release();
+
+        let mut set = self.lock.inflight.lock().unwrap();
+        while set.contains(path) {
+            set = self.lock.cvar.wait(set).unwrap();
+        }
+        set.insert(path.to_path_buf());
+        self.path = Some(path.to_path_buf());
+    }
+
+    fn release (&mut self) {
+        if let Some(path) = self.path.take() {
+            let mut set = self.lock.inflight.lock().unwrap();
+            set.remove(&path);
+            self.lock.cvar.notify_all();
+        }
+    }
+}
+
+impl<'a> Drop for TargetClaim<'a> {
+    fn drop (&mut self) {
+        self.release();
+    }
+}
+
+//
+// Place `file` at `targ`, using `mode` to decide whether that means
+// symlinking, copying, or moving.  `link_src` is the (possibly relative)
+// path the symlink should point at; it's unused for Copy/Move, which
+// operate on `file` directly.  If `targ` already exists we hash both
+// files rather than blindly overwriting: identical content is reported
+// and skipped, differing content gets a disambiguated name instead of
+// clobbering either file. This is what keeps two different photos with
+// the same capture-second timestamp from colliding in `link_name`.
+//
+// `target_lock` claims `targ` (and, via `place_file_with_collision`, every
+// disambiguated candidate tried) for the whole check/hash/place sequence:
+// since this runs on a rayon worker thread, checking a path's existence
+// and then placing the file are two separate steps, and without a claim
+// two threads whose files land on the same path -- whether it's both
+// threads' original target, or one thread's original target matching
+// another's disambiguated candidate -- could both see it missing and
+// race each other into `fs::rename`/`fs::copy`, silently losing one of
+// the two files.  Threads working on unrelated targets never share a
+// claimed path and run concurrently.
+//
+fn place_file (file: &Path, link_src: &Path, targ: &Path, opts: &Options,
+               target_lock: &TargetLock, pb: &ProgressBar) -> Result<(), Error> {
+    let mut claim = target_lock.claim(targ);
+
+    if targ.exists() {
+        place_file_with_collision(file, link_src, targ, opts, &mut claim, pb)
+    } else {
+        place_file_unchecked(opts.mode, file, link_src, targ, opts.no_execute, pb)
+    }
+}
 
-        println!("ln -s {:?} {:?}", src, targ);
-        if !no_execute {
-            use std::os::unix::fs;
+fn place_file_with_collision (file: &Path, link_src: &Path, targ: &Path,
+                               opts: &Options, claim: &mut TargetClaim,
+                               pb: &ProgressBar) -> Result<(), Error> {
+    let src_hash = hash_file(file)?;
+    let targ_hash = hash_file(targ)?;
+
+    if src_hash == targ_hash {
+        if opts.verbose {
+            say(pb, format!("# already backed up: {:?} == {:?}", file, targ));
+        } else {
+            say(pb, format!("# already backed up: {:?}", targ));
+        }
+        return Ok(());
+    }
 
-            if let Err(e) = fs::symlink(src, targ) {
-                println!("# error: {:?}", e);
-            }                
+    for n in 1..1000 {
+        let candidate = disambiguate(targ, n);
+        claim.switch(&candidate);
+        if candidate.exists() {
+            if hash_file(&candidate)? == src_hash {
+                say(pb, format!("# already backed up: {:?}", candidate));
+                return Ok(());
+            }
+            continue;
         }
+
+        say(pb, format!("# conflict: '{:?}' already exists with different content; using '{:?}' instead",
+                  targ, candidate));
+        return place_file_unchecked(opts.mode, file, link_src, &candidate,
+                                     opts.no_execute, pb);
     }
+
+    Err(Error::Conflict { path: targ.to_path_buf() })
+}
+
+//
+// Append "-<n>" to the target's file stem, keeping its extension, to
+// disambiguate from a pre-existing file with different content.
+//
+fn disambiguate (targ: &Path, n: usize) -> PathBuf {
+    let stem = targ.file_stem().map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut name = format!("{}-{}", stem, n);
+    if let Some(ext) = targ.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    targ.with_file_name(name)
+}
+
+fn place_file_unchecked (mode: LinkMode, file: &Path, link_src: &Path,
+                          targ: &Path, no_execute: bool, pb: &ProgressBar)
+                          -> Result<(), Error> {
+    match mode {
+        LinkMode::Symlink => {
+            say(pb, format!("ln -s {:?} {:?}", link_src, targ));
+            if !no_execute {
+                use std::os::unix::fs as unix_fs;
+                unix_fs::symlink(link_src, targ)
+                    .context(Symlink { path: targ.to_path_buf() })?;
+            }
+        },
+        LinkMode::Copy => {
+            say(pb, format!("cp {:?} {:?}", file, targ));
+            if !no_execute {
+                fs::copy(file, targ).context(CopyFailed { path: targ.to_path_buf() })?;
+            }
+        },
+        LinkMode::Move => {
+            say(pb, format!("mv {:?} {:?}", file, targ));
+            if !no_execute {
+                fs::rename(file, targ).context(MoveFailed { path: targ.to_path_buf() })?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+//
+// Stream a SHA-256 hash of a file's contents, so we can tell whether two
+// files at a colliding target path are actually the same photo.
+//
+fn hash_file (path: &Path) -> Result<[u8; 32], Error> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = File::open(path).context(Open { path })?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).context(Hash { path })?;
+    Ok(hasher.finalize().into())
 }
 
-fn get_exif (path: &Path, verbose: bool) -> Result<Exif, Error> {
+fn get_exif (path: &Path, verbose: bool, min_size: u64, pb: &ProgressBar)
+             -> Result<ExifData, Error> {
     let file = File::open(path).context(Open { path })?;
 
-    if let Ok(i) = file.metadata() {
-        if i.len() < 100 * 1024 {
-            return Err(Error::TooSmall { path: path.to_path_buf(),
-                                         len: i.len() });
+    if min_size > 0 && !is_exiftool_ext(path) {
+        if let Ok(i) = file.metadata() {
+            if i.len() < min_size {
+                return Err(Error::TooSmall { path: path.to_path_buf(),
+                                             len: i.len() });
+            }
         }
     }
 
     let mut bufreader = std::io::BufReader::new(&file);
     let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader)
-        .context(Parse{ path })?;
-    
+    let exif = match exifreader.read_from_container(&mut bufreader) {
+        Ok(exif) => exif,
+        Err(_) => {
+            if verbose {
+                say(pb, "# native exif parse failed, falling back to exiftool");
+            }
+            return get_exif_via_exiftool(path, verbose, pb);
+        },
+    };
+
     for f in exif.fields() {
         if verbose && f.tag != exif::Tag::MakerNote {
-            println!("# '{}' [{}] :: '{}'",
-                     f.tag, f.ifd_num, f.display_value().with_unit(&exif));
+            say(pb, format!("# '{}' [{}] :: '{}'",
+                       f.tag, f.ifd_num, f.display_value().with_unit(&exif)));
         }
     }
 
-    Ok(exif)
+    Ok(ExifData::Native(exif))
 }
 
-fn link_name (exif: &Exif, path: &Path, base: &str, verbose: bool)
-              -> Result<PathBuf, Error> {
-    let datetime = first_of(path, &exif, &DATETIME)?;
-    if verbose {
-        println!("# datetime '{}'", datetime);
+//
+// Shell out to `exiftool` for formats the exif crate can't parse (video
+// containers, HEIC, most RAW formats).  We only ask it for the one field
+// we care about, which keeps the output small and the parsing simple.
+//
+fn get_exif_via_exiftool (path: &Path, verbose: bool, pb: &ProgressBar)
+                          -> Result<ExifData, Error> {
+    // Ask exiftool to print CreateDate in DATE_FORMAT directly, rather
+    // than its default colon-separated date (%Y:%m:%d), so it lines up
+    // with the dash-separated dates the native exif path produces.
+    let output = Command::new("exiftool")
+        .arg("-json")
+        .arg("-d")
+        .arg(DATE_FORMAT)
+        .arg("-CreateDate")
+        .arg(path)
+        .output()
+        .context(ExiftoolSpawn { path })?;
+
+    if !output.status.success() {
+        return Err(Error::ExiftoolFailed { path: path.to_path_buf(),
+                                           status: output.status.code()
+                                               .unwrap_or(-1) });
     }
 
-    let res = DATE_REGEX.replace_all(&datetime, "$y/$m/$d/$H-$M-$S-");
+    let entries: Vec<ExiftoolEntry> = serde_json::from_slice(&output.stdout)
+        .context(ExiftoolParse { path })?;
+
+    let date = entries.into_iter().next()
+        .and_then(|e| e.create_date)
+        .ok_or_else(|| Error::ExiftoolNoDate { path: path.to_path_buf() })?;
+
     if verbose {
-        println!("# datetime '{}'", res);
+        say(pb, format!("# exiftool CreateDate '{}'", date));
     }
-    
+
+    Ok(ExifData::Exiftool(date))
+}
+
+fn link_name (exif: &ExifData, path: &Path, opts: &Options, pb: &ProgressBar)
+              -> Result<PathBuf, Error> {
+    let (datetime, source) = match first_of(path, &exif, &DATETIME) {
+        Ok(v) => v,
+        Err(e) => {
+            if !opts.mtime_fallback {
+                return Err(e);
+            }
+            (mtime_date(path)?, DateSource::Mtime)
+        },
+    };
+    if opts.verbose {
+        say(pb, format!("# datetime '{}' (source: {:?})", datetime, source));
+    }
+
+    let naive = parse_exif_date(path, &datetime)?;
+    let dir = naive.format(opts.template).to_string();
+    if opts.verbose {
+        say(pb, format!("# directory '{}'", dir));
+    }
+
     let mut target = OsString::new();
-    target.push(base);
+    target.push(opts.base);
     target.push("/");
-    target.push(res.to_string());
+    target.push(dir);
 
     let s = {
         match path.file_name() {
@@ -276,22 +791,55 @@ fn link_name (exif: &Exif, path: &Path, base: &str, verbose: bool)
     let s2 = s.replace(" ", "-");
     target.push(PathBuf::from(s2));
 
-    if verbose {
-        println!("# target {:?}", target);
+    if opts.verbose {
+        say(pb, format!("# target {:?}", target));
     }
 
     Ok(PathBuf::from(target))
 }
 
+//
+// Parse a date pulled from EXIF/exiftool/mtime.  DATE_FORMAT ("%Y-%m-%d
+// %H:%M:%S") is what every source is expected to produce, but we also
+// accept exiftool's own default colon-separated date ("%Y:%m:%d
+// %H:%M:%S") as a fallback in case `-d` isn't honored by an older
+// exiftool build, so a format mismatch between sources doesn't silently
+// drop the file.
+//
+const FALLBACK_DATE_FORMATS: &[&str] = &["%Y:%m:%d %H:%M:%S"];
+
+fn parse_exif_date (path: &Path, raw: &str) -> Result<chrono::NaiveDateTime, Error> {
+    let mut last_err = match chrono::NaiveDateTime::parse_from_str(raw, DATE_FORMAT) {
+        Ok(naive) => return Ok(naive),
+        Err(e) => e,
+    };
+
+    for fmt in FALLBACK_DATE_FORMATS {
+        match chrono::NaiveDateTime::parse_from_str(raw, fmt) {
+            Ok(naive) => return Ok(naive),
+            Err(e) => last_err = e,
+        }
+    }
+
+    Err(Error::TemplateDate { path: path.to_path_buf(), raw: raw.to_string(),
+                              source: last_err })
+}
+
 //
 /// Look through the EXIF data for a set of fields, returning the first one.
 /// Tags are exif::Tag::* values (e.g. exif::Tag::DateTimeDigitized).
 //
-fn first_of (path: &Path, exif: &Exif, tags: &[exif::Tag])
-             -> Result<String, Error> {
+fn first_of (path: &Path, exif: &ExifData, tags: &[exif::Tag])
+             -> Result<(String, DateSource), Error> {
+    let exif = match exif {
+        ExifData::Exiftool(date) => return Ok((date.clone(), DateSource::Exiftool)),
+        ExifData::Mtime(date) => return Ok((date.clone(), DateSource::Mtime)),
+        ExifData::Native(exif) => exif,
+    };
+
     for tag in tags {
         if let Some(value) = exif.get_field(*tag, exif::In::PRIMARY) {
-            return Ok(value.display_value().to_string())
+            return Ok((value.display_value().to_string(), DateSource::Exif))
         }
     }
 
@@ -301,3 +849,81 @@ fn first_of (path: &Path, exif: &Exif, tags: &[exif::Tag])
                               field: tags[0].description()
                               .unwrap_or("[unknown]").to_string() })
 }
+
+//
+// Last-resort fallback: use the file's modification time (or, if that's
+// unavailable, its creation time) as the photo's date, formatted to match
+// what DATE_FORMAT expects from EXIF/exiftool dates.
+//
+fn mtime_date (path: &Path) -> Result<String, Error> {
+    let metadata = fs::metadata(path).context(Metadata { path })?;
+    let mtime = metadata.modified().or_else(|_| metadata.created())
+        .context(Metadata { path })?;
+
+    let datetime: chrono::DateTime<chrono::Local> = mtime.into();
+    Ok(datetime.format(DATE_FORMAT).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disambiguate_appends_suffix_before_extension() {
+        let targ = PathBuf::from("/out/2024/01/01/12-00-00-IMG_0001.jpg");
+        assert_eq!(disambiguate(&targ, 1),
+                   PathBuf::from("/out/2024/01/01/12-00-00-IMG_0001-1.jpg"));
+        assert_eq!(disambiguate(&targ, 2),
+                   PathBuf::from("/out/2024/01/01/12-00-00-IMG_0001-2.jpg"));
+    }
+
+    #[test]
+    fn disambiguate_without_extension() {
+        let targ = PathBuf::from("/out/2024/01/01/12-00-00-README");
+        assert_eq!(disambiguate(&targ, 1),
+                   PathBuf::from("/out/2024/01/01/12-00-00-README-1"));
+    }
+
+    #[test]
+    fn parse_exif_date_accepts_date_format() {
+        let path = PathBuf::from("img.jpg");
+        let naive = parse_exif_date(&path, "2024-01-02 03:04:05").unwrap();
+        assert_eq!(naive.format(DATE_FORMAT).to_string(), "2024-01-02 03:04:05");
+    }
+
+    #[test]
+    fn parse_exif_date_accepts_exiftool_colon_fallback() {
+        let path = PathBuf::from("img.jpg");
+        let naive = parse_exif_date(&path, "2024:01:02 03:04:05").unwrap();
+        assert_eq!(naive.format(DATE_FORMAT).to_string(), "2024-01-02 03:04:05");
+    }
+
+    #[test]
+    fn parse_exif_date_rejects_garbage() {
+        let path = PathBuf::from("img.jpg");
+        assert!(parse_exif_date(&path, "not a date").is_err());
+    }
+
+    #[test]
+    fn parse_size_accepts_plain_bytes_and_suffixes() {
+        assert_eq!(parse_size("12345"), Ok(12345));
+        assert_eq!(parse_size("100K"), Ok(100 * 1024));
+        assert_eq!(parse_size("2m"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_size("0"), Ok(0));
+    }
+
+    #[test]
+    fn parse_size_rejects_garbage() {
+        assert!(parse_size("not-a-size").is_err());
+        assert!(parse_size("12X").is_err());
+    }
+
+    #[test]
+    fn is_exiftool_ext_matches_video_and_raw_case_insensitively() {
+        assert!(is_exiftool_ext(Path::new("clip.MOV")));
+        assert!(is_exiftool_ext(Path::new("photo.cr2")));
+        assert!(!is_exiftool_ext(Path::new("photo.jpg")));
+        assert!(!is_exiftool_ext(Path::new("no_extension")));
+    }
+}